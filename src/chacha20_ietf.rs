@@ -1,4 +1,4 @@
-use crate::ChachaPolyError;
+use crate::{ chacha20_simd::chacha20_x4, ChachaPolyError };
 use crypto_api::{
 	cipher::{ CipherInfo, Cipher },
 	rng::{ SecureRng, SecKeyGen }
@@ -14,28 +14,29 @@ const CHACHA20_MAX: usize = 4_294_967_296 * 64; // 2^32 * BLOCK_SIZE
 const CHACHA20_MAX: usize = usize::max_value(); // 2^32 - 1
 
 
-/// Computes the `n`th ChaCha20 block with `key` and `nonce` into `buf`
-fn chacha20(key: &[u8], nonce: &[u8], n: u32, buf: &mut[u8]) {
+/// Computes the `n`th ChaCha20 block with `key` and `nonce` into `buf`, running `double_rounds`
+/// double-rounds (`10` for ChaCha20, `6` for ChaCha12, `4` for ChaCha8)
+pub(in crate) fn chacha20(key: &[u8], nonce: &[u8], n: u32, buf: &mut[u8], double_rounds: usize) {
 	// ChaCha20 constants
 	const CONSTANTS: [u32; 4] = [0x61707865, 0x3320646e, 0x79622d32, 0x6b206574];
-	
+
 	// Read key and nonce
-	let mut key_words = vec![0; 8];
+	let mut key_words = [0u32; 8];
 	for i in 0..8 { key_words[i] =   read32_le!(  &key[i * 4..]) }
-	
-	let mut nonce_words = vec![0; 3];
+
+	let mut nonce_words = [0u32; 3];
 	for i in 0..3 { nonce_words[i] = read32_le!(&nonce[i * 4..]) }
-	
-	
+
+
 	// Compute block
-	let mut state = vec![0u32; 16];
+	let mut state = [0u32; 16];
 	state[ 0.. 4].copy_from_slice(&CONSTANTS);
 	state[ 4..12].copy_from_slice(&key_words);
 	state[12] = n;
 	state[13..16].copy_from_slice(&nonce_words);
-	
+
 	// Compute double-rounds
-	for _ in 0..10 {
+	for _ in 0..double_rounds {
 		/// A ChaCha20 quarterround
 		macro_rules! quarterround {
 			($a:expr, $b:expr, $c:expr, $d:expr) => ({
@@ -73,9 +74,121 @@ fn chacha20(key: &[u8], nonce: &[u8], n: u32, buf: &mut[u8]) {
 }
 
 
+/// Derives a 32-byte subkey from `key` and a 16-byte `nonce` using HChaCha20
+///
+/// This runs the same double-rounds as `chacha20`, but places `nonce` in words 12..16 instead of
+/// the counter/nonce layout and skips the final addition of the initial state, as specified for
+/// HChaCha20.
+fn hchacha20(key: &[u8], nonce: &[u8], buf: &mut[u8]) {
+	// ChaCha20 constants
+	const CONSTANTS: [u32; 4] = [0x61707865, 0x3320646e, 0x79622d32, 0x6b206574];
+
+	// Read key and nonce
+	let mut key_words = [0u32; 8];
+	for i in 0..8 { key_words[i] = read32_le!(&key[i * 4..]) }
+
+	let mut nonce_words = [0u32; 4];
+	for i in 0..4 { nonce_words[i] = read32_le!(&nonce[i * 4..]) }
+
+	// Compute block
+	let mut state = [0u32; 16];
+	state[ 0.. 4].copy_from_slice(&CONSTANTS);
+	state[ 4..12].copy_from_slice(&key_words);
+	state[12..16].copy_from_slice(&nonce_words);
+
+	// Compute double-rounds
+	for _ in 0..10 {
+		/// A ChaCha20 quarterround
+		macro_rules! quarterround {
+			($a:expr, $b:expr, $c:expr, $d:expr) => ({
+				state[$a] = add!(state[$a], state[$b]);
+				state[$d] = xor!(state[$d], state[$a]);
+				state[$d] = or!(shl!(state[$d], 16), shr!(state[$d], 16));
+				state[$c] = add!(state[$c], state[$d]);
+				state[$b] = xor!(state[$b], state[$c]);
+				state[$b] = or!(shl!(state[$b], 12), shr!(state[$b], 20));
+				state[$a] = add!(state[$a], state[$b]);
+				state[$d] = xor!(state[$d], state[$a]);
+				state[$d] = or!(shl!(state[$d],  8), shr!(state[$d], 24));
+				state[$c] = add!(state[$c], state[$d]);
+				state[$b] = xor!(state[$b], state[$c]);
+				state[$b] = or!(shl!(state[$b],  7), shr!(state[$b], 25));
+			});
+		}
+
+		// Perform 8 quarterrounds (2 rounds)
+		quarterround!( 0,  4,  8, 12);
+		quarterround!( 1,  5,  9, 13);
+		quarterround!( 2,  6, 10, 14);
+		quarterround!( 3,  7, 11, 15);
+		quarterround!( 0,  5, 10, 15);
+		quarterround!( 1,  6, 11, 12);
+		quarterround!( 2,  7,  8, 13);
+		quarterround!( 3,  4,  9, 14);
+	}
+
+	// The subkey is state words 0..4 and 12..16 -- *without* adding back the initial state
+	for i in  0.. 4 { write32_le!(state[i    ] => &mut buf[i * 4..]) }
+	for i in 12..16 { write32_le!(state[i    ] => &mut buf[(i - 8) * 4..]) }
+}
+
+
+/// Returns an error if advancing the block counter from `n` by the number of blocks needed to
+/// process `data_len` bytes would wrap the 32-bit block counter past `2^32` blocks
+///
+/// Reusing counter `0` after a wrap would produce the same keystream again, silently destroying
+/// confidentiality, so this is checked up front and rejected rather than risked.
+fn check_counter_space(n: u32, data_len: usize) -> Result<(), Box<dyn Error + 'static>> {
+	let blocks_needed = ((data_len + 63) / 64) as u64;
+	if n as u64 + blocks_needed > 1u64 << 32 {
+		Err(ChachaPolyError::ApiMisuse("Message too large for the remaining counter space"))?
+	}
+	Ok(())
+}
+
+/// XORs the bytes in `data` with the ChaCha-keystream for `key` and `nonce` starting at the
+/// `n`th block, running `double_rounds` double-rounds per block
+///
+/// Processes four blocks at a time through `chacha20_simd::chacha20_x4`, which dispatches to a
+/// vectorized backend if the running CPU supports one, falling back to the scalar `chacha20` for
+/// the remaining fewer than four blocks (the last of which may be partial). Fails without touching
+/// `data` if `n` would wrap before all of it is processed.
+fn xor_rounds(key: &[u8], nonce: &[u8], mut n: u32, mut data: &mut[u8], double_rounds: usize)
+	-> Result<(), Box<dyn Error + 'static>>
+{
+	check_counter_space(n, data.len())?;
+
+	let mut buf4 = [0u8; 256];
+	while data.len() >= buf4.len() {
+		chacha20_x4(key, nonce, n, &mut buf4, double_rounds);
+		n = n.wrapping_add(4);
+
+		for i in 0..buf4.len() { data[i] = xor!(data[i], buf4[i]) }
+		data = &mut data[buf4.len()..];
+	}
+
+	let mut buf = [0u8; 64];
+	while !data.is_empty() {
+		// Compute next block
+		chacha20(key, nonce, n, &mut buf, double_rounds);
+		n = n.wrapping_add(1);
+
+		// Xor block
+		let to_xor = min(data.len(), buf.len());
+		for i in 0..to_xor { data[i] = xor!(data[i], buf[i]) }
+		data = &mut data[to_xor..];
+	}
+
+	Ok(())
+}
+
+
 /// An implementation of [ChaCha20 (IETF-version)](https://tools.ietf.org/html/rfc8439)
 pub struct ChaCha20Ietf;
 impl ChaCha20Ietf {
+	/// The number of double-rounds ChaCha20 performs per block
+	const DOUBLE_ROUNDS: usize = 10;
+
 	/// Creates a `Cipher` instance with `ChaCha20Ietf` as underlying cipher
 	pub fn cipher() -> Box<dyn Cipher> {
 		Box::new(Self)
@@ -83,18 +196,14 @@ impl ChaCha20Ietf {
 	
 	/// XORs the bytes in `data` with the ChaCha20-keystream for `key` and `nonce` starting at the
 	/// `n`th block
-	pub(in crate) fn xor(key: &[u8], nonce: &[u8], mut n: u32, mut data: &mut[u8]) {
-		let mut buf = vec![0; 64];
-		while !data.is_empty() {
-			// Compute next block
-			chacha20(key, nonce, n, &mut buf);
-			n += 1;
-			
-			// Xor block
-			let to_xor = min(data.len(), buf.len());
-			for i in 0..to_xor { data[i] = xor!(data[i], buf[i]) }
-			data = &mut data[to_xor..];
-		}
+	///
+	/// Processes four blocks at a time through `chacha20_simd::chacha20_x4`, which dispatches to
+	/// a vectorized backend if the running CPU supports one, falling back to the scalar
+	/// `chacha20` for the remaining fewer than four blocks (the last of which may be partial).
+	pub(in crate) fn xor(key: &[u8], nonce: &[u8], n: u32, data: &mut[u8])
+		-> Result<(), Box<dyn Error + 'static>>
+	{
+		xor_rounds(key, nonce, n, data, Self::DOUBLE_ROUNDS)
 	}
 }
 impl SecKeyGen for ChaCha20Ietf {
@@ -129,7 +238,188 @@ impl Cipher for ChaCha20Ietf {
 		if plaintext_len > buf.len() { Err(ChachaPolyError::ApiMisuse("Buffer is too small"))? }
 		
 		// Encrypt the data
-		Self::xor(key, nonce, 0, &mut buf[..plaintext_len]);
+		Self::xor(key, nonce, 0, &mut buf[..plaintext_len])?;
+		Ok(plaintext_len)
+	}
+	fn encrypt_to(&self, buf: &mut[u8], plaintext: &[u8], key: &[u8], nonce: &[u8])
+		-> Result<usize, Box<dyn Error + 'static>>
+	{
+		// Check input
+		if key.len() != 32 { Err(ChachaPolyError::ApiMisuse("Invalid key length"))? }
+		if nonce.len() != 12 { Err(ChachaPolyError::ApiMisuse("Invalid nonce length"))? }
+		if plaintext.len() > CHACHA20_MAX { Err(ChachaPolyError::ApiMisuse("Too much data"))? }
+		if plaintext.len() > buf.len() { Err(ChachaPolyError::ApiMisuse("Buffer is too small"))? }
+		
+		// Ensure the keystream can be produced before writing any plaintext into `buf`
+		check_counter_space(0, plaintext.len())?;
+
+		// Fill `buf` and encrypt the data in place
+		buf[..plaintext.len()].copy_from_slice(plaintext);
+		Self::xor(key, nonce, 0, &mut buf[..plaintext.len()])?;
+		Ok(plaintext.len())
+	}
+	
+	fn decrypt(&self, buf: &mut[u8], ciphertext_len: usize, key: &[u8], nonce: &[u8])
+		-> Result<usize, Box<dyn Error + 'static>>
+	{
+		self.encrypt(buf, ciphertext_len, key, nonce)
+	}
+	fn decrypt_to(&self, buf: &mut[u8], ciphertext: &[u8], key: &[u8], nonce: &[u8])
+		-> Result<usize, Box<dyn Error + 'static>>
+	{
+		self.encrypt_to(buf, ciphertext, key, nonce)
+	}
+}
+
+
+/// An implementation of [XChaCha20](https://tools.ietf.org/html/draft-irtf-cfrg-xchacha-03) with
+/// a 24-byte (192-bit) nonce
+///
+/// The first 16 bytes of the nonce are used together with `key` to derive a subkey through
+/// HChaCha20; the remaining 8 bytes are then used as part of a regular `ChaCha20Ietf` nonce,
+/// prefixed with four zero bytes. This allows nonces to be chosen randomly without having to
+/// worry about the birthday bound that applies to `ChaCha20Ietf`'s 12-byte nonce.
+pub struct XChaCha20Ietf;
+impl XChaCha20Ietf {
+	/// Creates a `Cipher` instance with `XChaCha20Ietf` as underlying cipher
+	pub fn cipher() -> Box<dyn Cipher> {
+		Box::new(Self)
+	}
+
+	/// XORs the bytes in `data` with the XChaCha20-keystream for `key` and the 24-byte `nonce`
+	/// starting at the `n`th block
+	pub(in crate) fn xor(key: &[u8], nonce: &[u8], n: u32, data: &mut[u8])
+		-> Result<(), Box<dyn Error + 'static>>
+	{
+		// Derive the subkey and build the inner 12-byte nonce
+		let mut subkey = vec![0; 32];
+		hchacha20(key, &nonce[..16], &mut subkey);
+
+		let mut inner_nonce = vec![0; 12];
+		inner_nonce[4..12].copy_from_slice(&nonce[16..24]);
+
+		ChaCha20Ietf::xor(&subkey, &inner_nonce, n, data)
+	}
+}
+impl SecKeyGen for XChaCha20Ietf {
+	fn new_sec_key(&self, buf: &mut[u8], rng: &mut SecureRng)
+		-> Result<usize, Box<dyn Error + 'static>>
+	{
+		// Validate buffer and generate key
+		if buf.len() < 32 { Err(ChachaPolyError::ApiMisuse("Buffer is too small"))? }
+		rng.random(&mut buf[..32])?;
+		Ok(32)
+	}
+}
+impl Cipher for XChaCha20Ietf {
+	fn info(&self) -> CipherInfo {
+		CipherInfo {
+			name: "XChaCha20Ietf", is_otc: true,
+			key_len_r: 32..32, nonce_len_r: 24..24, aead_tag_len_r: 0..0
+		}
+	}
+
+	fn encrypted_len_max(&self, plaintext_len: usize) -> usize {
+		plaintext_len
+	}
+
+	fn encrypt(&self, buf: &mut[u8], plaintext_len: usize, key: &[u8], nonce: &[u8])
+		-> Result<usize, Box<dyn Error + 'static>>
+	{
+		// Check input
+		if key.len() != 32 { Err(ChachaPolyError::ApiMisuse("Invalid key length"))? }
+		if nonce.len() != 24 { Err(ChachaPolyError::ApiMisuse("Invalid nonce length"))? }
+		if plaintext_len > CHACHA20_MAX { Err(ChachaPolyError::ApiMisuse("Too much data"))? }
+		if plaintext_len > buf.len() { Err(ChachaPolyError::ApiMisuse("Buffer is too small"))? }
+
+		// Encrypt the data
+		Self::xor(key, nonce, 0, &mut buf[..plaintext_len])?;
+		Ok(plaintext_len)
+	}
+	fn encrypt_to(&self, buf: &mut[u8], plaintext: &[u8], key: &[u8], nonce: &[u8])
+		-> Result<usize, Box<dyn Error + 'static>>
+	{
+		// Check input
+		if key.len() != 32 { Err(ChachaPolyError::ApiMisuse("Invalid key length"))? }
+		if nonce.len() != 24 { Err(ChachaPolyError::ApiMisuse("Invalid nonce length"))? }
+		if plaintext.len() > CHACHA20_MAX { Err(ChachaPolyError::ApiMisuse("Too much data"))? }
+		if plaintext.len() > buf.len() { Err(ChachaPolyError::ApiMisuse("Buffer is too small"))? }
+
+		// Ensure the keystream can be produced before writing any plaintext into `buf`
+		check_counter_space(0, plaintext.len())?;
+
+		// Fill `buf` and encrypt the data in place
+		buf[..plaintext.len()].copy_from_slice(plaintext);
+		Self::xor(key, nonce, 0, &mut buf[..plaintext.len()])?;
+		Ok(plaintext.len())
+	}
+
+	fn decrypt(&self, buf: &mut[u8], ciphertext_len: usize, key: &[u8], nonce: &[u8])
+		-> Result<usize, Box<dyn Error + 'static>>
+	{
+		self.encrypt(buf, ciphertext_len, key, nonce)
+	}
+	fn decrypt_to(&self, buf: &mut[u8], ciphertext: &[u8], key: &[u8], nonce: &[u8])
+		-> Result<usize, Box<dyn Error + 'static>>
+	{
+		self.encrypt_to(buf, ciphertext, key, nonce)
+	}
+}
+
+
+/// An implementation of the reduced-round ChaCha8 variant (4 double-rounds instead of 10), for
+/// latency-sensitive callers who accept its smaller security margin
+pub struct ChaCha8Ietf;
+impl ChaCha8Ietf {
+	/// The number of double-rounds ChaCha8 performs per block
+	const DOUBLE_ROUNDS: usize = 4;
+	
+	/// Creates a `Cipher` instance with `ChaCha8Ietf` as underlying cipher
+	pub fn cipher() -> Box<dyn Cipher> {
+		Box::new(Self)
+	}
+	
+	/// XORs the bytes in `data` with the ChaCha8-keystream for `key` and `nonce` starting at the
+	/// `n`th block
+	pub(in crate) fn xor(key: &[u8], nonce: &[u8], n: u32, data: &mut[u8])
+		-> Result<(), Box<dyn Error + 'static>>
+	{
+		xor_rounds(key, nonce, n, data, Self::DOUBLE_ROUNDS)
+	}
+}
+impl SecKeyGen for ChaCha8Ietf {
+	fn new_sec_key(&self, buf: &mut[u8], rng: &mut SecureRng)
+		-> Result<usize, Box<dyn Error + 'static>>
+	{
+		// Validate buffer and generate key
+		if buf.len() < 32 { Err(ChachaPolyError::ApiMisuse("Buffer is too small"))? }
+		rng.random(&mut buf[..32])?;
+		Ok(32)
+	}
+}
+impl Cipher for ChaCha8Ietf {
+	fn info(&self) -> CipherInfo {
+		CipherInfo {
+			name: "ChaCha8Ietf", is_otc: true,
+			key_len_r: 32..32, nonce_len_r: 12..12, aead_tag_len_r: 0..0
+		}
+	}
+	
+	fn encrypted_len_max(&self, plaintext_len: usize) -> usize {
+		plaintext_len
+	}
+	
+	fn encrypt(&self, buf: &mut[u8], plaintext_len: usize, key: &[u8], nonce: &[u8])
+		-> Result<usize, Box<dyn Error + 'static>>
+	{
+		// Check input
+		if key.len() != 32 { Err(ChachaPolyError::ApiMisuse("Invalid key length"))? }
+		if nonce.len() != 12 { Err(ChachaPolyError::ApiMisuse("Invalid nonce length"))? }
+		if plaintext_len > CHACHA20_MAX { Err(ChachaPolyError::ApiMisuse("Too much data"))? }
+		if plaintext_len > buf.len() { Err(ChachaPolyError::ApiMisuse("Buffer is too small"))? }
+		
+		// Encrypt the data
+		Self::xor(key, nonce, 0, &mut buf[..plaintext_len])?;
 		Ok(plaintext_len)
 	}
 	fn encrypt_to(&self, buf: &mut[u8], plaintext: &[u8], key: &[u8], nonce: &[u8])
@@ -141,9 +431,12 @@ impl Cipher for ChaCha20Ietf {
 		if plaintext.len() > CHACHA20_MAX { Err(ChachaPolyError::ApiMisuse("Too much data"))? }
 		if plaintext.len() > buf.len() { Err(ChachaPolyError::ApiMisuse("Buffer is too small"))? }
 		
+		// Ensure the keystream can be produced before writing any plaintext into `buf`
+		check_counter_space(0, plaintext.len())?;
+
 		// Fill `buf` and encrypt the data in place
 		buf[..plaintext.len()].copy_from_slice(plaintext);
-		Self::xor(key, nonce, 0, &mut buf[..plaintext.len()]);
+		Self::xor(key, nonce, 0, &mut buf[..plaintext.len()])?;
 		Ok(plaintext.len())
 	}
 	
@@ -158,3 +451,299 @@ impl Cipher for ChaCha20Ietf {
 		self.encrypt_to(buf, ciphertext, key, nonce)
 	}
 }
+
+
+/// An implementation of the reduced-round ChaCha12 variant (6 double-rounds instead of 10), for
+/// latency-sensitive callers who accept its smaller security margin
+pub struct ChaCha12Ietf;
+impl ChaCha12Ietf {
+	/// The number of double-rounds ChaCha12 performs per block
+	const DOUBLE_ROUNDS: usize = 6;
+	
+	/// Creates a `Cipher` instance with `ChaCha12Ietf` as underlying cipher
+	pub fn cipher() -> Box<dyn Cipher> {
+		Box::new(Self)
+	}
+	
+	/// XORs the bytes in `data` with the ChaCha12-keystream for `key` and `nonce` starting at the
+	/// `n`th block
+	pub(in crate) fn xor(key: &[u8], nonce: &[u8], n: u32, data: &mut[u8])
+		-> Result<(), Box<dyn Error + 'static>>
+	{
+		xor_rounds(key, nonce, n, data, Self::DOUBLE_ROUNDS)
+	}
+}
+impl SecKeyGen for ChaCha12Ietf {
+	fn new_sec_key(&self, buf: &mut[u8], rng: &mut SecureRng)
+		-> Result<usize, Box<dyn Error + 'static>>
+	{
+		// Validate buffer and generate key
+		if buf.len() < 32 { Err(ChachaPolyError::ApiMisuse("Buffer is too small"))? }
+		rng.random(&mut buf[..32])?;
+		Ok(32)
+	}
+}
+impl Cipher for ChaCha12Ietf {
+	fn info(&self) -> CipherInfo {
+		CipherInfo {
+			name: "ChaCha12Ietf", is_otc: true,
+			key_len_r: 32..32, nonce_len_r: 12..12, aead_tag_len_r: 0..0
+		}
+	}
+	
+	fn encrypted_len_max(&self, plaintext_len: usize) -> usize {
+		plaintext_len
+	}
+	
+	fn encrypt(&self, buf: &mut[u8], plaintext_len: usize, key: &[u8], nonce: &[u8])
+		-> Result<usize, Box<dyn Error + 'static>>
+	{
+		// Check input
+		if key.len() != 32 { Err(ChachaPolyError::ApiMisuse("Invalid key length"))? }
+		if nonce.len() != 12 { Err(ChachaPolyError::ApiMisuse("Invalid nonce length"))? }
+		if plaintext_len > CHACHA20_MAX { Err(ChachaPolyError::ApiMisuse("Too much data"))? }
+		if plaintext_len > buf.len() { Err(ChachaPolyError::ApiMisuse("Buffer is too small"))? }
+		
+		// Encrypt the data
+		Self::xor(key, nonce, 0, &mut buf[..plaintext_len])?;
+		Ok(plaintext_len)
+	}
+	fn encrypt_to(&self, buf: &mut[u8], plaintext: &[u8], key: &[u8], nonce: &[u8])
+		-> Result<usize, Box<dyn Error + 'static>>
+	{
+		// Check input
+		if key.len() != 32 { Err(ChachaPolyError::ApiMisuse("Invalid key length"))? }
+		if nonce.len() != 12 { Err(ChachaPolyError::ApiMisuse("Invalid nonce length"))? }
+		if plaintext.len() > CHACHA20_MAX { Err(ChachaPolyError::ApiMisuse("Too much data"))? }
+		if plaintext.len() > buf.len() { Err(ChachaPolyError::ApiMisuse("Buffer is too small"))? }
+		
+		// Ensure the keystream can be produced before writing any plaintext into `buf`
+		check_counter_space(0, plaintext.len())?;
+
+		// Fill `buf` and encrypt the data in place
+		buf[..plaintext.len()].copy_from_slice(plaintext);
+		Self::xor(key, nonce, 0, &mut buf[..plaintext.len()])?;
+		Ok(plaintext.len())
+	}
+	
+	fn decrypt(&self, buf: &mut[u8], ciphertext_len: usize, key: &[u8], nonce: &[u8])
+		-> Result<usize, Box<dyn Error + 'static>>
+	{
+		self.encrypt(buf, ciphertext_len, key, nonce)
+	}
+	fn decrypt_to(&self, buf: &mut[u8], ciphertext: &[u8], key: &[u8], nonce: &[u8])
+		-> Result<usize, Box<dyn Error + 'static>>
+	{
+		self.encrypt_to(buf, ciphertext, key, nonce)
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const KEY: [u8; 32] = [0u8; 32];
+	const NONCE: [u8; 12] = [0u8; 12];
+
+	#[test]
+	fn check_counter_space_allows_exact_fit() {
+		// n = u32::MAX, zero further blocks needed: the last valid counter value, not yet wrapped
+		assert!(check_counter_space(u32::max_value(), 0).is_ok());
+		// One full block starting at the last valid counter value exactly exhausts the space
+		assert!(check_counter_space(u32::max_value(), 64).is_ok());
+	}
+
+	#[test]
+	fn check_counter_space_rejects_overflow() {
+		// One block would need counter value `u32::MAX + 1`, which does not exist
+		assert!(check_counter_space(u32::max_value(), 65).is_err());
+	}
+
+	#[test]
+	fn xor_rounds_errors_instead_of_wrapping_counter() {
+		// Near the top of the counter space: only one block's worth of room is left
+		let n = u32::max_value();
+		let mut data = [0u8; 128];
+		let before = data;
+
+		let result = xor_rounds(&KEY, &NONCE, n, &mut data, ChaCha20Ietf::DOUBLE_ROUNDS);
+
+		assert!(result.is_err());
+		// Must fail before touching `data`, not silently wrap the counter and keep going
+		assert_eq!(data, before);
+	}
+
+	#[test]
+	fn xor_rounds_succeeds_when_counter_space_is_sufficient() {
+		let n = u32::max_value() - 1;
+		let mut data = [0u8; 128];
+
+		assert!(xor_rounds(&KEY, &NONCE, n, &mut data, ChaCha20Ietf::DOUBLE_ROUNDS).is_ok());
+	}
+
+	/// An independent reference implementation of the ChaCha block function, written directly from
+	/// the spec rather than reusing this module's `read32_le!`/`add!`/`xor!`/`shl!`/`shr!`/
+	/// `write32_le!` macros, so the tests below can catch bugs in those macros or in how
+	/// `DOUBLE_ROUNDS` is threaded through `xor_rounds` and `chacha20_simd::chacha20_x4`
+	fn reference_chacha20(key: &[u8; 32], nonce: &[u8; 12], n: u32, double_rounds: usize) -> [u8; 64] {
+		const CONSTANTS: [u32; 4] = [0x61707865, 0x3320646e, 0x79622d32, 0x6b206574];
+
+		let mut state = [0u32; 16];
+		state[0..4].copy_from_slice(&CONSTANTS);
+		for i in 0..8 {
+			state[4 + i] = u32::from_le_bytes([
+				key[i * 4], key[i * 4 + 1], key[i * 4 + 2], key[i * 4 + 3]
+			]);
+		}
+		state[12] = n;
+		for i in 0..3 {
+			state[13 + i] = u32::from_le_bytes([
+				nonce[i * 4], nonce[i * 4 + 1], nonce[i * 4 + 2], nonce[i * 4 + 3]
+			]);
+		}
+
+		let initial = state;
+
+		fn quarterround(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+			state[a] = state[a].wrapping_add(state[b]);
+			state[d] = (state[d] ^ state[a]).rotate_left(16);
+			state[c] = state[c].wrapping_add(state[d]);
+			state[b] = (state[b] ^ state[c]).rotate_left(12);
+			state[a] = state[a].wrapping_add(state[b]);
+			state[d] = (state[d] ^ state[a]).rotate_left(8);
+			state[c] = state[c].wrapping_add(state[d]);
+			state[b] = (state[b] ^ state[c]).rotate_left(7);
+		}
+
+		for _ in 0..double_rounds {
+			quarterround(&mut state, 0, 4, 8, 12);
+			quarterround(&mut state, 1, 5, 9, 13);
+			quarterround(&mut state, 2, 6, 10, 14);
+			quarterround(&mut state, 3, 7, 11, 15);
+			quarterround(&mut state, 0, 5, 10, 15);
+			quarterround(&mut state, 1, 6, 11, 12);
+			quarterround(&mut state, 2, 7, 8, 13);
+			quarterround(&mut state, 3, 4, 9, 14);
+		}
+
+		let mut out = [0u8; 64];
+		for i in 0..16 {
+			out[i * 4..i * 4 + 4].copy_from_slice(&state[i].wrapping_add(initial[i]).to_le_bytes());
+		}
+		out
+	}
+
+	/// RFC 8439 S2.3.2 test vector for the ChaCha20 block function, used to anchor
+	/// `reference_chacha20` itself against a known-good external source before relying on it below
+	#[test]
+	fn reference_chacha20_matches_rfc8439_vector() {
+		let key: [u8; 32] = [
+			0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07,
+			0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+			0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17,
+			0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f
+		];
+		let nonce: [u8; 12] = [0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x00, 0x4a, 0x00, 0x00, 0x00, 0x00];
+		let expected: [u8; 64] = [
+			0x10, 0xf1, 0xe7, 0xe4, 0xd1, 0x3b, 0x59, 0x15, 0x50, 0x0f, 0xdd, 0x1f, 0xa3, 0x20, 0x71, 0xc4,
+			0xc7, 0xd1, 0xf4, 0xc7, 0x33, 0xc0, 0x68, 0x03, 0x04, 0x22, 0xaa, 0x9a, 0xc3, 0xd4, 0x6c, 0x4e,
+			0xd2, 0x82, 0x64, 0x46, 0x07, 0x9f, 0xaa, 0x09, 0x07, 0x70, 0xe5, 0xf2, 0x17, 0x4c, 0x92, 0x05,
+			0x52, 0xbb, 0xbc, 0x2b, 0xd1, 0xe0, 0xa9, 0x1e, 0x69, 0xf1, 0xe9, 0xcc, 0x4a, 0xce, 0xb7, 0xc7
+		];
+
+		assert_eq!(reference_chacha20(&key, &nonce, 1, 10), expected);
+	}
+
+	#[test]
+	fn chacha8_keystream_matches_reference_at_4_double_rounds() {
+		let key = [0x42u8; 32];
+		let nonce = [0x24u8; 12];
+
+		// A full 256-byte run exercises the `chacha20_x4` SIMD dispatch path, not just the scalar
+		// tail in `xor_rounds`
+		let mut data = [0u8; 256];
+		ChaCha8Ietf::xor(&key, &nonce, 0, &mut data).unwrap();
+
+		for block in 0..4 {
+			let expected = reference_chacha20(&key, &nonce, block as u32, ChaCha8Ietf::DOUBLE_ROUNDS);
+			assert_eq!(&data[block * 64..(block + 1) * 64], &expected[..]);
+		}
+	}
+
+	#[test]
+	fn chacha12_keystream_matches_reference_at_6_double_rounds() {
+		let key = [0x42u8; 32];
+		let nonce = [0x24u8; 12];
+
+		let mut data = [0u8; 256];
+		ChaCha12Ietf::xor(&key, &nonce, 0, &mut data).unwrap();
+
+		for block in 0..4 {
+			let expected = reference_chacha20(&key, &nonce, block as u32, ChaCha12Ietf::DOUBLE_ROUNDS);
+			assert_eq!(&data[block * 64..(block + 1) * 64], &expected[..]);
+		}
+	}
+
+	/// draft-irtf-cfrg-xchacha-03 appendix A.1 test vector for the HChaCha20 subkey derivation
+	#[test]
+	fn hchacha20_matches_draft_xchacha_vector() {
+		let key: [u8; 32] = [
+			0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07,
+			0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+			0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17,
+			0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f
+		];
+		let nonce: [u8; 16] = [
+			0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x00, 0x4a,
+			0x00, 0x00, 0x00, 0x00, 0x31, 0x41, 0x59, 0x27
+		];
+		let expected: [u8; 32] = [
+			0x82, 0x41, 0x3b, 0x42, 0x27, 0xb2, 0x7b, 0xfe,
+			0xd3, 0x0e, 0x42, 0x50, 0x8a, 0x87, 0x7d, 0x73,
+			0xa0, 0xf9, 0xe4, 0xd5, 0x8a, 0x74, 0xa8, 0x53,
+			0xc1, 0x2e, 0xc4, 0x13, 0x26, 0xd3, 0xec, 0xdc
+		];
+
+		let mut subkey = [0u8; 32];
+		hchacha20(&key, &nonce, &mut subkey);
+		assert_eq!(subkey, expected);
+	}
+
+	#[test]
+	fn xchacha20_xor_matches_hchacha20_plus_chacha20_composition() {
+		let key: [u8; 32] = [0x5au8; 32];
+		let nonce: [u8; 24] = [0x3cu8; 24];
+
+		// What `XChaCha20Ietf::xor` is specified to do: derive a subkey with HChaCha20 from the
+		// first 16 nonce bytes, then run `ChaCha20Ietf` with that subkey and a 12-byte nonce made
+		// of four zero bytes followed by the last 8 nonce bytes
+		let mut subkey = [0u8; 32];
+		hchacha20(&key, &nonce[..16], &mut subkey);
+		let mut inner_nonce = [0u8; 12];
+		inner_nonce[4..12].copy_from_slice(&nonce[16..24]);
+
+		let mut expected = [0u8; 256];
+		ChaCha20Ietf::xor(&subkey, &inner_nonce, 0, &mut expected).unwrap();
+
+		let mut actual = [0u8; 256];
+		XChaCha20Ietf::xor(&key, &nonce, 0, &mut actual).unwrap();
+
+		assert_eq!(actual, expected);
+	}
+
+	#[test]
+	fn xchacha20_encrypt_decrypt_round_trips() {
+		let key: [u8; 32] = [0x5au8; 32];
+		let nonce: [u8; 24] = [0x3cu8; 24];
+		let plaintext = b"Ladies and Gentlemen of the class of '99";
+
+		let mut buf = [0u8; 64];
+		let cipher = XChaCha20Ietf;
+		let ct_len = cipher.encrypt_to(&mut buf, plaintext, &key, &nonce).unwrap();
+		assert_ne!(&buf[..ct_len], &plaintext[..]);
+
+		let pt_len = cipher.decrypt(&mut buf, ct_len, &key, &nonce).unwrap();
+		assert_eq!(&buf[..pt_len], &plaintext[..]);
+	}
+}
@@ -0,0 +1,336 @@
+//! Vectorized backends that compute four ChaCha blocks (counters `n..n+4`) in parallel
+//!
+//! The scalar `chacha20` in `chacha20_ietf` computes one 64-byte block per call. The backends in
+//! this module instead run the requested number of double-rounds on four blocks at once, using
+//! SIMD instructions available on the running CPU (SSE2/AVX2 on `x86_64`, NEON on `aarch64`), with
+//! the scalar implementation as the portable fallback. The right backend is chosen once at
+//! runtime via feature detection; all backends are required to agree bit-for-bit with the scalar
+//! implementation and with the RFC 8439 test vectors.
+
+use crate::chacha20_ietf::chacha20;
+
+
+/// ChaCha20 constants
+const CONSTANTS: [u32; 4] = [0x61707865, 0x3320646e, 0x79622d32, 0x6b206574];
+
+/// Computes four ChaCha blocks (counters `n`, `n + 1`, `n + 2`, `n + 3`) for `key` and `nonce`
+/// into `out`, which must be exactly `256` bytes long (four 64-byte blocks, in order), running
+/// `double_rounds` double-rounds per block
+///
+/// Dispatches to the fastest backend the current CPU supports, falling back to the portable
+/// scalar implementation if none of the vectorized backends are available.
+pub(in crate) fn chacha20_x4(key: &[u8], nonce: &[u8], n: u32, out: &mut[u8], double_rounds: usize) {
+	assert_eq!(out.len(), 256);
+
+	#[cfg(target_arch = "x86_64")]
+	{
+		if is_x86_feature_detected!("avx2") {
+			return unsafe { avx2::chacha20_x4(key, nonce, n, out, double_rounds) };
+		}
+		if is_x86_feature_detected!("sse2") {
+			return unsafe { sse2::chacha20_x4(key, nonce, n, out, double_rounds) };
+		}
+	}
+	#[cfg(target_arch = "aarch64")]
+	{
+		if std::arch::is_aarch64_feature_detected!("neon") {
+			return unsafe { neon::chacha20_x4(key, nonce, n, out, double_rounds) };
+		}
+	}
+
+	portable::chacha20_x4(key, nonce, n, out, double_rounds)
+}
+
+
+/// The portable, non-vectorized fallback: four scalar blocks computed with stack-allocated state
+mod portable {
+	pub(in crate::chacha20_simd) fn chacha20_x4(
+		key: &[u8], nonce: &[u8], n: u32, out: &mut[u8], double_rounds: usize
+	) {
+		for i in 0..4 {
+			super::chacha20(
+				key, nonce, n.wrapping_add(i as u32), &mut out[i * 64..(i + 1) * 64], double_rounds
+			);
+		}
+	}
+}
+
+
+/// SSE2 backend: one 128-bit lane per block, one vector per state word
+#[cfg(target_arch = "x86_64")]
+mod sse2 {
+	use std::arch::x86_64::*;
+
+	#[target_feature(enable = "sse2")]
+	pub(in crate::chacha20_simd) unsafe fn chacha20_x4(
+		key: &[u8], nonce: &[u8], n: u32, out: &mut[u8], double_rounds: usize
+	) {
+		macro_rules! read32_le { ($b:expr) => (u32::from_le_bytes([$b[0], $b[1], $b[2], $b[3]])) }
+
+		let mut key_words = [0u32; 8];
+		for i in 0..8 { key_words[i] = read32_le!(&key[i * 4..]) }
+		let mut nonce_words = [0u32; 3];
+		for i in 0..3 { nonce_words[i] = read32_le!(&nonce[i * 4..]) }
+
+		// One vector per state word; word 12 (the counter) carries a different value per lane
+		let mut v: [__m128i; 16] = [_mm_setzero_si128(); 16];
+		for i in 0..4 { v[i] = _mm_set1_epi32(super::CONSTANTS[i] as i32) }
+		for i in 0..8 { v[4 + i] = _mm_set1_epi32(key_words[i] as i32) }
+		v[12] = _mm_set_epi32(
+			n.wrapping_add(3) as i32, n.wrapping_add(2) as i32,
+			n.wrapping_add(1) as i32, n as i32
+		);
+		for i in 0..3 { v[13 + i] = _mm_set1_epi32(nonce_words[i] as i32) }
+
+		let initial = v;
+
+		// `_mm_slli_epi32`/`_mm_srli_epi32` require compile-time immediate shift amounts, so each
+		// rotation distance gets its own function rather than a `bits: i32` parameter
+		#[inline(always)]
+		unsafe fn rotl_16(x: __m128i) -> __m128i {
+			_mm_or_si128(_mm_slli_epi32(x, 16), _mm_srli_epi32(x, 16))
+		}
+		#[inline(always)]
+		unsafe fn rotl_12(x: __m128i) -> __m128i {
+			_mm_or_si128(_mm_slli_epi32(x, 12), _mm_srli_epi32(x, 20))
+		}
+		#[inline(always)]
+		unsafe fn rotl_8(x: __m128i) -> __m128i {
+			_mm_or_si128(_mm_slli_epi32(x, 8), _mm_srli_epi32(x, 24))
+		}
+		#[inline(always)]
+		unsafe fn rotl_7(x: __m128i) -> __m128i {
+			_mm_or_si128(_mm_slli_epi32(x, 7), _mm_srli_epi32(x, 25))
+		}
+
+		for _ in 0..double_rounds {
+			macro_rules! quarterround {
+				($a:expr, $b:expr, $c:expr, $d:expr) => ({
+					v[$a] = _mm_add_epi32(v[$a], v[$b]);
+					v[$d] = rotl_16(_mm_xor_si128(v[$d], v[$a]));
+					v[$c] = _mm_add_epi32(v[$c], v[$d]);
+					v[$b] = rotl_12(_mm_xor_si128(v[$b], v[$c]));
+					v[$a] = _mm_add_epi32(v[$a], v[$b]);
+					v[$d] = rotl_8(_mm_xor_si128(v[$d], v[$a]));
+					v[$c] = _mm_add_epi32(v[$c], v[$d]);
+					v[$b] = rotl_7(_mm_xor_si128(v[$b], v[$c]));
+				});
+			}
+
+			quarterround!( 0,  4,  8, 12);
+			quarterround!( 1,  5,  9, 13);
+			quarterround!( 2,  6, 10, 14);
+			quarterround!( 3,  7, 11, 15);
+			quarterround!( 0,  5, 10, 15);
+			quarterround!( 1,  6, 11, 12);
+			quarterround!( 2,  7,  8, 13);
+			quarterround!( 3,  4,  9, 14);
+		}
+
+		for i in 0..16 { v[i] = _mm_add_epi32(v[i], initial[i]) }
+
+		// Transpose: `words[i]` holds state word `i` for all four blocks, one per lane
+		let mut words = [[0u32; 4]; 16];
+		for i in 0..16 { _mm_storeu_si128(words[i].as_mut_ptr() as *mut __m128i, v[i]) }
+
+		for block in 0..4 {
+			for word in 0..16 {
+				let bytes = words[word][block].to_le_bytes();
+				out[block * 64 + word * 4..block * 64 + word * 4 + 4].copy_from_slice(&bytes);
+			}
+		}
+	}
+}
+
+
+/// AVX2 dispatch target: currently just re-runs the 128-bit `sse2` implementation
+///
+/// This does not yet use 256-bit AVX2 vectors, so it carries no speed advantage over calling
+/// `sse2::chacha20_x4` directly -- `chacha20_x4`'s dispatcher only reaches this module because
+/// every CPU with AVX2 also has SSE2, not because this code takes advantage of the wider
+/// registers. A real AVX2 backend would pack two blocks per lane-pair of a `__m256i` and is left
+/// as a follow-up; until then this module exists so the dispatcher's `is_x86_feature_detected!`
+/// order doesn't need to special-case "AVX2 present but no AVX2-specific backend".
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+	use std::arch::x86_64::*;
+
+	#[target_feature(enable = "avx2")]
+	pub(in crate::chacha20_simd) unsafe fn chacha20_x4(
+		key: &[u8], nonce: &[u8], n: u32, out: &mut[u8], double_rounds: usize
+	) {
+		// AVX2 CPUs always support SSE2; reuse its 128-bit (4-lane) implementation
+		super::sse2::chacha20_x4(key, nonce, n, out, double_rounds)
+	}
+}
+
+
+/// NEON backend: one 128-bit lane per block, one vector per state word
+#[cfg(target_arch = "aarch64")]
+mod neon {
+	use std::arch::aarch64::*;
+
+	#[target_feature(enable = "neon")]
+	pub(in crate::chacha20_simd) unsafe fn chacha20_x4(
+		key: &[u8], nonce: &[u8], n: u32, out: &mut[u8], double_rounds: usize
+	) {
+		macro_rules! read32_le { ($b:expr) => (u32::from_le_bytes([$b[0], $b[1], $b[2], $b[3]])) }
+
+		let mut key_words = [0u32; 8];
+		for i in 0..8 { key_words[i] = read32_le!(&key[i * 4..]) }
+		let mut nonce_words = [0u32; 3];
+		for i in 0..3 { nonce_words[i] = read32_le!(&nonce[i * 4..]) }
+
+		let mut v: [uint32x4_t; 16] = [vdupq_n_u32(0); 16];
+		for i in 0..4 { v[i] = vdupq_n_u32(super::CONSTANTS[i]) }
+		for i in 0..8 { v[4 + i] = vdupq_n_u32(key_words[i]) }
+		v[12] = {
+			let counters = [n, n.wrapping_add(1), n.wrapping_add(2), n.wrapping_add(3)];
+			vld1q_u32(counters.as_ptr())
+		};
+		for i in 0..3 { v[13 + i] = vdupq_n_u32(nonce_words[i]) }
+
+		let initial = v;
+
+		// `vshlq_n_u32`/`vshrq_n_u32` require compile-time immediate shift amounts, so each
+		// rotation distance gets its own function rather than a `bits: i32` parameter
+		#[inline(always)]
+		unsafe fn rotl_16(x: uint32x4_t) -> uint32x4_t {
+			vorrq_u32(vshlq_n_u32(x, 16), vshrq_n_u32(x, 16))
+		}
+		#[inline(always)]
+		unsafe fn rotl_12(x: uint32x4_t) -> uint32x4_t {
+			vorrq_u32(vshlq_n_u32(x, 12), vshrq_n_u32(x, 20))
+		}
+		#[inline(always)]
+		unsafe fn rotl_8(x: uint32x4_t) -> uint32x4_t {
+			vorrq_u32(vshlq_n_u32(x, 8), vshrq_n_u32(x, 24))
+		}
+		#[inline(always)]
+		unsafe fn rotl_7(x: uint32x4_t) -> uint32x4_t {
+			vorrq_u32(vshlq_n_u32(x, 7), vshrq_n_u32(x, 25))
+		}
+
+		for _ in 0..double_rounds {
+			macro_rules! quarterround {
+				($a:expr, $b:expr, $c:expr, $d:expr) => ({
+					v[$a] = vaddq_u32(v[$a], v[$b]);
+					v[$d] = rotl_16(veorq_u32(v[$d], v[$a]));
+					v[$c] = vaddq_u32(v[$c], v[$d]);
+					v[$b] = rotl_12(veorq_u32(v[$b], v[$c]));
+					v[$a] = vaddq_u32(v[$a], v[$b]);
+					v[$d] = rotl_8(veorq_u32(v[$d], v[$a]));
+					v[$c] = vaddq_u32(v[$c], v[$d]);
+					v[$b] = rotl_7(veorq_u32(v[$b], v[$c]));
+				});
+			}
+
+			quarterround!( 0,  4,  8, 12);
+			quarterround!( 1,  5,  9, 13);
+			quarterround!( 2,  6, 10, 14);
+			quarterround!( 3,  7, 11, 15);
+			quarterround!( 0,  5, 10, 15);
+			quarterround!( 1,  6, 11, 12);
+			quarterround!( 2,  7,  8, 13);
+			quarterround!( 3,  4,  9, 14);
+		}
+
+		for i in 0..16 { v[i] = vaddq_u32(v[i], initial[i]) }
+
+		let mut words = [[0u32; 4]; 16];
+		for i in 0..16 { vst1q_u32(words[i].as_mut_ptr(), v[i]) }
+
+		for block in 0..4 {
+			for word in 0..16 {
+				let bytes = words[word][block].to_le_bytes();
+				out[block * 64 + word * 4..block * 64 + word * 4 + 4].copy_from_slice(&bytes);
+			}
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// RFC 8439 S2.3.2 test vector for the ChaCha20 block function
+	const KEY: [u8; 32] = [
+		0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07,
+		0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+		0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17,
+		0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f
+	];
+	const NONCE: [u8; 12] = [
+		0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x00, 0x4a, 0x00, 0x00, 0x00, 0x00
+	];
+	const BLOCK_1: [u8; 64] = [
+		0x10, 0xf1, 0xe7, 0xe4, 0xd1, 0x3b, 0x59, 0x15, 0x50, 0x0f, 0xdd, 0x1f, 0xa3, 0x20, 0x71, 0xc4,
+		0xc7, 0xd1, 0xf4, 0xc7, 0x33, 0xc0, 0x68, 0x03, 0x04, 0x22, 0xaa, 0x9a, 0xc3, 0xd4, 0x6c, 0x4e,
+		0xd2, 0x82, 0x64, 0x46, 0x07, 0x9f, 0xaa, 0x09, 0x07, 0x70, 0xe5, 0xf2, 0x17, 0x4c, 0x92, 0x05,
+		0x52, 0xbb, 0xbc, 0x2b, 0xd1, 0xe0, 0xa9, 0x1e, 0x69, 0xf1, 0xe9, 0xcc, 0x4a, 0xce, 0xb7, 0xc7
+	];
+
+	#[test]
+	fn portable_matches_rfc8439() {
+		let mut out = [0u8; 256];
+		portable::chacha20_x4(&KEY, &NONCE, 1, &mut out, 10);
+		assert_eq!(&out[..64], &BLOCK_1[..]);
+	}
+
+	#[test]
+	#[cfg(target_arch = "x86_64")]
+	fn sse2_agrees_with_portable() {
+		if !is_x86_feature_detected!("sse2") { return }
+
+		let mut expected = [0u8; 256];
+		portable::chacha20_x4(&KEY, &NONCE, 1, &mut expected, 10);
+
+		let mut actual = [0u8; 256];
+		unsafe { sse2::chacha20_x4(&KEY, &NONCE, 1, &mut actual, 10) };
+
+		assert_eq!(&actual[..], &expected[..]);
+		assert_eq!(&actual[..64], &BLOCK_1[..]);
+	}
+
+	#[test]
+	#[cfg(target_arch = "x86_64")]
+	fn avx2_agrees_with_portable() {
+		if !is_x86_feature_detected!("avx2") { return }
+
+		let mut expected = [0u8; 256];
+		portable::chacha20_x4(&KEY, &NONCE, 1, &mut expected, 10);
+
+		let mut actual = [0u8; 256];
+		unsafe { avx2::chacha20_x4(&KEY, &NONCE, 1, &mut actual, 10) };
+
+		assert_eq!(&actual[..], &expected[..]);
+		assert_eq!(&actual[..64], &BLOCK_1[..]);
+	}
+
+	#[test]
+	#[cfg(target_arch = "aarch64")]
+	fn neon_agrees_with_portable() {
+		if !std::arch::is_aarch64_feature_detected!("neon") { return }
+
+		let mut expected = [0u8; 256];
+		portable::chacha20_x4(&KEY, &NONCE, 1, &mut expected, 10);
+
+		let mut actual = [0u8; 256];
+		unsafe { neon::chacha20_x4(&KEY, &NONCE, 1, &mut actual, 10) };
+
+		assert_eq!(&actual[..], &expected[..]);
+		assert_eq!(&actual[..64], &BLOCK_1[..]);
+	}
+
+	#[test]
+	fn dispatch_agrees_with_portable() {
+		let mut expected = [0u8; 256];
+		portable::chacha20_x4(&KEY, &NONCE, 1, &mut expected, 10);
+
+		let mut actual = [0u8; 256];
+		chacha20_x4(&KEY, &NONCE, 1, &mut actual, 10);
+
+		assert_eq!(&actual[..], &expected[..]);
+	}
+}
@@ -0,0 +1,155 @@
+use crate::{ chacha20_ietf::chacha20, ChachaPolyError };
+use std::error::Error;
+
+
+/// A stateful ChaCha20 keystream that can be fed data in arbitrary-sized chunks
+///
+/// Unlike `ChaCha20Ietf`'s `Cipher` implementation, whose every call restarts at block `0`,
+/// `ChaCha20Stream` keeps track of the current block counter and the offset into the current keystream block across calls to
+/// `apply`. This makes it possible to encrypt/decrypt a stream (e.g. a socket) without buffering
+/// the whole message upfront.
+pub struct ChaCha20Stream {
+	key: Vec<u8>,
+	nonce: Vec<u8>,
+	n: u32,
+	buf: [u8; 64],
+	offset: usize,
+	counter_exhausted: bool
+}
+impl ChaCha20Stream {
+	/// The length of one ChaCha20 keystream block in bytes
+	const BLOCK_LEN: usize = 64;
+
+	/// Creates a new `ChaCha20Stream` for `key` and `nonce`, starting at block `0`
+	pub fn new(key: &[u8], nonce: &[u8]) -> Result<Self, Box<dyn Error + 'static>> {
+		if key.len() != 32 { Err(ChachaPolyError::ApiMisuse("Invalid key length"))? }
+		if nonce.len() != 12 { Err(ChachaPolyError::ApiMisuse("Invalid nonce length"))? }
+
+		Ok(Self {
+			key: key.to_vec(), nonce: nonce.to_vec(),
+			n: 0, buf: [0; 64], offset: 64, counter_exhausted: false
+		})
+	}
+
+	/// Advances the block counter past the block just computed at `self.n`, or, if `self.n` was
+	/// already the last valid counter value, marks the stream as exhausted instead of wrapping it
+	/// back to `0`
+	///
+	/// Wrapping the counter back to `0` would reuse a previous keystream block, silently
+	/// destroying confidentiality, so this is refused rather than risked.
+	fn advance_counter(&mut self) {
+		if self.n == u32::max_value() { self.counter_exhausted = true } else { self.n += 1 }
+	}
+
+	/// XORs `data` in place with the next bytes of the keystream
+	///
+	/// The keystream offset left over from the previous call is carried over, and new blocks are
+	/// computed on demand as `data` consumes the current block. Fails without touching `data` if
+	/// the keystream has already been used up to the end of the counter space; if `data` runs past
+	/// that end, the bytes before it are XORed and an error is returned for the rest.
+	pub fn apply(&mut self, mut data: &mut[u8]) -> Result<(), Box<dyn Error + 'static>> {
+		while !data.is_empty() {
+			// Refill the keystream buffer if it is exhausted
+			if self.offset == self.buf.len() {
+				if self.counter_exhausted {
+					Err(ChachaPolyError::ApiMisuse("Message too large for the remaining counter space"))?
+				}
+
+				chacha20(&self.key, &self.nonce, self.n, &mut self.buf, 10);
+				self.advance_counter();
+				self.offset = 0;
+			}
+
+			// Xor as much of `data` as is available in the buffered block
+			let to_xor = std::cmp::min(data.len(), self.buf.len() - self.offset);
+			for i in 0..to_xor { data[i] = xor!(data[i], self.buf[self.offset + i]) }
+
+			self.offset += to_xor;
+			data = &mut data[to_xor..];
+		}
+		Ok(())
+	}
+
+	/// Positions the keystream at `byte_offset`, so that the next call to `apply` continues as
+	/// if exactly `byte_offset` bytes had already been processed
+	///
+	/// This allows decrypting/re-encrypting an arbitrary sub-range of a large message (e.g. for
+	/// random-access file/disk encryption) without processing everything from the start. If
+	/// `byte_offset` lands in the very last block of the counter space, the stream is left exhausted,
+	/// just as `apply` would leave it after consuming that same block in sequence.
+	pub fn seek(&mut self, byte_offset: u64) -> Result<(), Box<dyn Error + 'static>> {
+		let block = byte_offset / Self::BLOCK_LEN as u64;
+		if block > u32::max_value() as u64 { Err(ChachaPolyError::ApiMisuse("Offset too large"))? }
+
+		self.n = block as u32;
+		self.counter_exhausted = false;
+		chacha20(&self.key, &self.nonce, self.n, &mut self.buf, 10);
+		self.advance_counter();
+		self.offset = (byte_offset % Self::BLOCK_LEN as u64) as usize;
+		Ok(())
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const KEY: [u8; 32] = [0x7au8; 32];
+	const NONCE: [u8; 12] = [0x11u8; 12];
+
+	#[test]
+	fn apply_in_chunks_matches_apply_in_one_shot() {
+		let mut chunked = [0x42u8; 200];
+		let mut stream = ChaCha20Stream::new(&KEY, &NONCE).unwrap();
+		stream.apply(&mut chunked[..70]).unwrap();
+		stream.apply(&mut chunked[70..130]).unwrap();
+		stream.apply(&mut chunked[130..]).unwrap();
+
+		let mut one_shot = [0x42u8; 200];
+		ChaCha20Stream::new(&KEY, &NONCE).unwrap().apply(&mut one_shot).unwrap();
+
+		assert_eq!(chunked, one_shot);
+	}
+
+	#[test]
+	fn apply_errors_instead_of_wrapping_counter() {
+		let mut stream = ChaCha20Stream::new(&KEY, &NONCE).unwrap();
+		// Force the stream to the brink of the counter space without depending on `seek`
+		stream.n = u32::max_value();
+		stream.offset = ChaCha20Stream::BLOCK_LEN;
+
+		// Consume the last valid block, then ask for one more byte of keystream
+		let mut last_block = [0u8; 64];
+		stream.apply(&mut last_block).unwrap();
+
+		let mut data = [0u8; 1];
+		let before = data;
+		assert!(stream.apply(&mut data).is_err());
+		assert_eq!(data, before);
+	}
+
+	#[test]
+	fn seek_to_block_boundary_matches_processing_from_the_start() {
+		let mut from_start = [0x99u8; 256];
+		ChaCha20Stream::new(&KEY, &NONCE).unwrap().apply(&mut from_start).unwrap();
+
+		let mut from_seek = [0x99u8; 64];
+		let mut stream = ChaCha20Stream::new(&KEY, &NONCE).unwrap();
+		stream.seek(192).unwrap();
+		stream.apply(&mut from_seek).unwrap();
+
+		assert_eq!(from_seek, from_start[192..256]);
+	}
+
+	#[test]
+	fn seek_to_last_block_then_apply_past_it_errors() {
+		let mut stream = ChaCha20Stream::new(&KEY, &NONCE).unwrap();
+		stream.seek(u32::max_value() as u64 * ChaCha20Stream::BLOCK_LEN as u64).unwrap();
+
+		// The seek itself consumes the last valid block; asking for keystream beyond it must fail
+		// rather than silently wrap the counter back to `0`
+		let mut data = [0u8; 65];
+		assert!(stream.apply(&mut data).is_err());
+	}
+}
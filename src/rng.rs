@@ -0,0 +1,135 @@
+use crate::{ chacha20_ietf::chacha20, ChachaPolyError };
+use crypto_api::rng::SecureRng;
+use std::error::Error;
+
+
+/// A deterministic pseudo-random generator built on the ChaCha20 keystream
+///
+/// Output always continues from the current position in the keystream; apart from `reseed`,
+/// which restarts at block `0`, there is no method to move to another position.
+///
+/// `ChaCha20Rng` treats the keystream for a given `key` and `nonce` as an all-zero "plaintext"
+/// run through `chacha20`, i.e. it emits the raw keystream bytes. Because the keystream is fully
+/// determined by `key`, `nonce` and the block counter, the same seed always reproduces the same
+/// byte stream -- useful for reproducible tests and for deriving nonces/keys without depending on
+/// an external RNG crate.
+pub struct ChaCha20Rng {
+	key: Vec<u8>,
+	nonce: Vec<u8>,
+	n: u32,
+	buf: [u8; 64],
+	offset: usize,
+	counter_exhausted: bool
+}
+impl ChaCha20Rng {
+	/// Creates a new `ChaCha20Rng` seeded with `key` and `nonce`, starting at block `0`
+	pub fn new(key: &[u8], nonce: &[u8]) -> Result<Self, Box<dyn Error + 'static>> {
+		if key.len() != 32 { Err(ChachaPolyError::ApiMisuse("Invalid key length"))? }
+		if nonce.len() != 12 { Err(ChachaPolyError::ApiMisuse("Invalid nonce length"))? }
+
+		Ok(Self {
+			key: key.to_vec(), nonce: nonce.to_vec(),
+			n: 0, buf: [0; 64], offset: 64, counter_exhausted: false
+		})
+	}
+
+	/// Reseeds this generator with a new `key` and `nonce`, resetting the block counter to `0`
+	pub fn reseed(&mut self, key: &[u8], nonce: &[u8]) -> Result<(), Box<dyn Error + 'static>> {
+		if key.len() != 32 { Err(ChachaPolyError::ApiMisuse("Invalid key length"))? }
+		if nonce.len() != 12 { Err(ChachaPolyError::ApiMisuse("Invalid nonce length"))? }
+
+		self.key.copy_from_slice(key);
+		self.nonce.copy_from_slice(nonce);
+		self.n = 0;
+		self.offset = 64;
+		self.counter_exhausted = false;
+		Ok(())
+	}
+
+	/// Fills `dest` with the next bytes of the ChaCha20 keystream
+	///
+	/// Fails without touching `dest` if the keystream has already been used up to the end of the
+	/// counter space; if `dest` runs past that end, the bytes before it are filled and an error is
+	/// returned for the rest. The counter never wraps: reusing counter `0` would repeat a previous
+	/// output, silently destroying the generator's unpredictability.
+	pub fn fill(&mut self, mut dest: &mut[u8]) -> Result<(), Box<dyn Error + 'static>> {
+		while !dest.is_empty() {
+			// Refill the keystream buffer if it is exhausted
+			if self.offset == self.buf.len() {
+				if self.counter_exhausted {
+					Err(ChachaPolyError::ApiMisuse("Requested more output than the counter space allows"))?
+				}
+
+				chacha20(&self.key, &self.nonce, self.n, &mut self.buf, 10);
+				if self.n == u32::max_value() { self.counter_exhausted = true } else { self.n += 1 }
+				self.offset = 0;
+			}
+
+			// Copy as much of `dest` as is available in the buffered block
+			let to_copy = std::cmp::min(dest.len(), self.buf.len() - self.offset);
+			dest[..to_copy].copy_from_slice(&self.buf[self.offset..self.offset + to_copy]);
+
+			self.offset += to_copy;
+			dest = &mut dest[to_copy..];
+		}
+		Ok(())
+	}
+}
+impl SecureRng for ChaCha20Rng {
+	fn random(&mut self, buf: &mut[u8]) -> Result<(), Box<dyn Error + 'static>> {
+		self.fill(buf)
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const KEY: [u8; 32] = [0x7au8; 32];
+	const NONCE: [u8; 12] = [0x11u8; 12];
+
+	#[test]
+	fn fill_in_chunks_matches_fill_in_one_shot() {
+		let mut chunked = [0u8; 200];
+		let mut rng = ChaCha20Rng::new(&KEY, &NONCE).unwrap();
+		rng.fill(&mut chunked[..70]).unwrap();
+		rng.fill(&mut chunked[70..130]).unwrap();
+		rng.fill(&mut chunked[130..]).unwrap();
+
+		let mut one_shot = [0u8; 200];
+		ChaCha20Rng::new(&KEY, &NONCE).unwrap().fill(&mut one_shot).unwrap();
+
+		assert_eq!(chunked, one_shot);
+	}
+
+	#[test]
+	fn reseed_resets_the_counter_and_keystream() {
+		let mut rng = ChaCha20Rng::new(&KEY, &NONCE).unwrap();
+		let mut first = [0u8; 64];
+		rng.fill(&mut first).unwrap();
+
+		rng.reseed(&KEY, &NONCE).unwrap();
+		let mut second = [0u8; 64];
+		rng.fill(&mut second).unwrap();
+
+		assert_eq!(first, second);
+	}
+
+	#[test]
+	fn fill_errors_instead_of_wrapping_counter() {
+		let mut rng = ChaCha20Rng::new(&KEY, &NONCE).unwrap();
+		// Force the generator to the brink of the counter space without a public seek API
+		rng.n = u32::max_value();
+		rng.offset = 64;
+
+		// Consume the last valid block, then ask for one more byte of keystream
+		let mut last_block = [0u8; 64];
+		rng.fill(&mut last_block).unwrap();
+
+		let mut dest = [0u8; 1];
+		let before = dest;
+		assert!(rng.fill(&mut dest).is_err());
+		assert_eq!(dest, before);
+	}
+}